@@ -1,12 +1,25 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, Semaphore};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::time::timeout;
 use reqwest::Client;
 use clap::{Arg, App};
 use lazy_static::lazy_static;
+use serde::Serialize;
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, OwnedTrustAnchor, RootCertStore};
+use tokio_rustls::TlsConnector;
+use x509_parser::prelude::*;
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hickory_resolver::proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_resolver::proto::rr::{Name, RData, RecordType};
 
 // Limit concurrency to prevent system overload
 const MAX_CONCURRENT_REQUESTS: usize = 100; // Adjust based on system performance
@@ -14,6 +27,127 @@ const MAX_CONCURRENT_REQUESTS: usize = 100; // Adjust based on system performanc
 lazy_static! {
     static ref LIVE_FILE: Arc<Mutex<BufWriter<File>>> = Arc::new(Mutex::new(BufWriter::new(File::create("live.txt").unwrap())));
     static ref DEAD_FILE: Arc<Mutex<BufWriter<File>>> = Arc::new(Mutex::new(BufWriter::new(File::create("dead.txt").unwrap())));
+    static ref NXDOMAIN_FILE: Arc<Mutex<BufWriter<File>>> = Arc::new(Mutex::new(BufWriter::new(File::create("nxdomain.txt").unwrap())));
+    static ref CERTS_FILE: Arc<Mutex<BufWriter<File>>> = Arc::new(Mutex::new(BufWriter::new(File::create("certs.txt").unwrap())));
+    static ref BOGUS_FILE: Arc<Mutex<BufWriter<File>>> = Arc::new(Mutex::new(BufWriter::new(File::create("bogus.txt").unwrap())));
+    static ref RESULTS: Arc<Mutex<Vec<DomainResult>>> = Arc::new(Mutex::new(Vec::new()));
+}
+
+// Outcome of the DNS pre-check stage, kept distinct from HTTP-level liveness.
+// `Bogus` is only reachable via `--doh --dnssec`, when the resolver doesn't
+// assert that the answer was authenticated. `Resolved` carries the addresses
+// the pre-check stage found, so later stages (e.g. `--tls-info`) can connect
+// through the same resolution path instead of re-resolving via the system
+// resolver.
+enum DnsOutcome {
+    Resolved(Vec<IpAddr>),
+    NxDomain,
+    Bogus,
+    ResolutionError,
+}
+
+// A reusable concurrency-handle abstraction around a semaphore. A single
+// handle is acquired per domain and held across its retry attempts, so a
+// storm of retrying tasks can never exceed MAX_CONCURRENT_REQUESTS.
+#[derive(Clone)]
+struct MaxHandles {
+    semaphore: Arc<Semaphore>,
+}
+
+impl MaxHandles {
+    fn new(limit: usize) -> Self {
+        MaxHandles { semaphore: Arc::new(Semaphore::new(limit)) }
+    }
+
+    async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.semaphore.clone().acquire_owned().await.unwrap()
+    }
+}
+
+// Retry behavior for transient HTTP failures
+#[derive(Clone)]
+struct RetryPolicy {
+    retries: u32,
+    backoff: Duration,
+    retry_statuses: Vec<u16>,
+}
+
+impl RetryPolicy {
+    fn should_retry(&self, status: Option<u16>, errored: bool) -> bool {
+        errored || status.is_some_and(|s| self.retry_statuses.contains(&s))
+    }
+
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt)
+    }
+}
+
+// Leaf certificate details extracted during `--tls-info` inspection
+struct CertInfo {
+    issuer: String,
+    subject: String,
+    sans: Vec<String>,
+    not_before: String,
+    not_after: String,
+    expires_soon: bool,
+}
+
+// The output format for `--output-format`
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Ndjson,
+    Csv,
+}
+
+impl OutputFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(format!("unknown output format '{}' (expected text, ndjson, or csv)", other)),
+        }
+    }
+}
+
+// A structured, per-domain outcome record. Emitted as ndjson/csv when
+// `--output-format` requests it, and always collected for the summary.
+#[derive(Clone, Serialize)]
+struct DomainResult {
+    domain: String,
+    final_url: Option<String>,
+    status: String,
+    scheme_used: Option<String>,
+    latency_ms: u128,
+    error_kind: Option<String>,
+}
+
+impl DomainResult {
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            csv_field(&self.domain),
+            csv_field(self.final_url.as_deref().unwrap_or("")),
+            csv_field(&self.status),
+            csv_field(self.scheme_used.as_deref().unwrap_or("")),
+            self.latency_ms,
+            csv_field(self.error_kind.as_deref().unwrap_or("")),
+        )
+    }
+}
+
+// RFC 4180 field quoting: wrap in double quotes (doubling any embedded
+// quotes) when the field contains a comma, quote, or newline. `final_url` is
+// server-controlled (it's the redirect target reqwest landed on) and
+// routinely contains commas in query strings, so every field is escaped
+// rather than just the ones that happen to need it today.
+fn csv_field(value: &str) -> String {
+    if value.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
 }
 
 #[tokio::main]
@@ -32,21 +166,138 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .short('v')
             .long("verbose")
             .help("Enables verbose output"))
+        .arg(Arg::with_name("dns-only")
+            .long("dns-only")
+            .help("Stop after DNS resolution instead of checking HTTP/HTTPS"))
+        .arg(Arg::with_name("resolver")
+            .long("resolver")
+            .value_name("IP:PORT")
+            .help("Use this DNS resolver instead of the system resolver")
+            .takes_value(true))
+        .arg(Arg::with_name("proxy")
+            .long("proxy")
+            .value_name("URL")
+            .help("Route requests through this HTTP/HTTPS/SOCKS5 proxy")
+            .takes_value(true))
+        .arg(Arg::with_name("redirects")
+            .long("redirects")
+            .value_name("N")
+            .help("Follow up to N redirects (default: 10)")
+            .takes_value(true))
+        .arg(Arg::with_name("user-agent")
+            .long("user-agent")
+            .value_name("STRING")
+            .help("Set the User-Agent header sent with each request")
+            .takes_value(true))
+        .arg(Arg::with_name("insecure")
+            .long("insecure")
+            .help("Accept invalid/expired/self-signed TLS certificates"))
+        .arg(Arg::with_name("tls-info")
+            .long("tls-info")
+            .help("For each live HTTPS domain, inspect its certificate and write certs.txt"))
+        .arg(Arg::with_name("expiry-warn-days")
+            .long("expiry-warn-days")
+            .value_name("DAYS")
+            .help("Flag certificates expiring within this many days (default: 30)")
+            .takes_value(true))
+        .arg(Arg::with_name("retries")
+            .long("retries")
+            .value_name("N")
+            .help("Retry each HTTP and HTTPS request up to N times before marking the domain dead (default: 0)")
+            .takes_value(true))
+        .arg(Arg::with_name("retry-backoff")
+            .long("retry-backoff")
+            .value_name("MS")
+            .help("Base delay between retries, doubled each attempt (default: 200)")
+            .takes_value(true))
+        .arg(Arg::with_name("retry-on-status")
+            .long("retry-on-status")
+            .value_name("CODES")
+            .help("Comma-separated status codes that trigger a retry (default: 429,500,502,503,504)")
+            .takes_value(true))
+        .arg(Arg::with_name("output-format")
+            .long("output-format")
+            .value_name("text|ndjson|csv")
+            .help("Emit a structured record per domain instead of plain text (default: text)")
+            .takes_value(true))
+        .arg(Arg::with_name("doh")
+            .long("doh")
+            .value_name("URL")
+            .help("Resolve over DNS-over-HTTPS (RFC 8484) using this endpoint instead of --resolver")
+            .takes_value(true))
+        .arg(Arg::with_name("dnssec")
+            .long("dnssec")
+            .help("With --doh, require the resolver to assert the answer is DNSSEC-authenticated (trusts the resolver's AD bit; does not independently verify the RRSIG/DNSKEY/DS chain)"))
         .get_matches();
 
     let filename = matches.value_of("file").unwrap();
     let verbose = matches.is_present("verbose");
+    let dns_only = matches.is_present("dns-only");
+    let resolver_addr = matches.value_of("resolver");
+    // reqwest's own default (absent an explicit `.redirect(...)` call) is
+    // `Policy::limited(10)`; match that here so omitting --redirects doesn't
+    // silently stop following redirects that were previously followed.
+    let redirects: usize = matches.value_of("redirects")
+        .map(|n| n.parse())
+        .transpose()?
+        .unwrap_or(10);
+    let tls_info = matches.is_present("tls-info");
+    let expiry_warn_days: i64 = matches.value_of("expiry-warn-days")
+        .map(|n| n.parse())
+        .transpose()?
+        .unwrap_or(30);
+    let output_format = matches.value_of("output-format")
+        .map(OutputFormat::parse)
+        .transpose()?
+        .unwrap_or(OutputFormat::Text);
+    let doh_url = matches.value_of("doh").map(str::to_string);
+    let want_dnssec = matches.is_present("dnssec");
+    if want_dnssec {
+        // See the doc comment on resolve_via_doh: this is AD-bit trust in the
+        // DoH resolver, not an independent RRSIG/DNSKEY/DS chain validation.
+        // Say so loudly so a compromised or lying resolver isn't mistaken
+        // for a verified one.
+        eprintln!("warning: --dnssec trusts the DoH resolver's AD bit; it does not independently verify the DNSSEC chain of trust up to the root");
+    }
+
+    let retry_statuses: Vec<u16> = match matches.value_of("retry-on-status") {
+        Some(list) => list.split(',').map(|s| s.trim().parse()).collect::<Result<_, _>>()?,
+        None => vec![429, 500, 502, 503, 504],
+    };
+    let retry_policy = RetryPolicy {
+        retries: matches.value_of("retries").map(|n| n.parse()).transpose()?.unwrap_or(0),
+        backoff: Duration::from_millis(matches.value_of("retry-backoff").map(|n| n.parse()).transpose()?.unwrap_or(200)),
+        retry_statuses,
+    };
 
-    let client = Client::builder()
+    let mut client_builder = Client::builder()
         .timeout(Duration::from_secs(10))
-        .build()?;
+        .redirect(reqwest::redirect::Policy::limited(redirects))
+        .danger_accept_invalid_certs(matches.is_present("insecure"));
+
+    if let Some(ua) = matches.value_of("user-agent") {
+        client_builder = client_builder.user_agent(ua);
+    }
+
+    if let Some(proxy_url) = matches.value_of("proxy") {
+        client_builder = client_builder.proxy(reqwest::Proxy::all(proxy_url)?);
+    }
+
+    let client = client_builder.build()?;
+
+    let resolver = Arc::new(build_resolver(resolver_addr)?);
 
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
 
-    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+    let handles_guard = MaxHandles::new(MAX_CONCURRENT_REQUESTS);
 
-    println!("Checking domains...");
+    if output_format == OutputFormat::Csv {
+        println!("domain,final_url,status,scheme_used,latency_ms,error_kind");
+    }
+    if output_format == OutputFormat::Text {
+        println!("Checking domains...");
+    }
 
     let mut handles = Vec::new();
     for line in reader.lines() {
@@ -54,15 +305,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Ok(d) => d.trim().to_string(),
             Err(_) => continue,
         };
+        if domain.is_empty() {
+            continue;
+        }
 
         let client = client.clone();
+        let resolver = Arc::clone(&resolver);
+        let doh_url = doh_url.clone();
         let verbose = verbose;
-        let semaphore = Arc::clone(&semaphore);
+        let dns_only = dns_only;
+        let tls_info = tls_info;
+        let retry_policy = retry_policy.clone();
+        let handles_guard = handles_guard.clone();
 
-        // Control concurrency using a semaphore
+        // Acquire a concurrency handle for the whole lifetime of this domain's
+        // check, including any retries, so retries can't exceed the cap.
         let handle = tokio::spawn(async move {
-            let _permit = semaphore.acquire().await.unwrap();
-            check_domain(&client, &domain, verbose).await;
+            let _permit = handles_guard.acquire().await;
+            let start = Instant::now();
+
+            let dns_outcome = match &doh_url {
+                Some(url) => resolve_via_doh(&client, url, &domain, want_dnssec).await,
+                None => resolve_domain(&resolver, &domain).await,
+            };
+
+            let mut result = match dns_outcome {
+                DnsOutcome::NxDomain => {
+                    write_to_file(&NXDOMAIN_FILE, &domain).await;
+                    DomainResult {
+                        domain: domain.clone(),
+                        final_url: None,
+                        status: "nxdomain".to_string(),
+                        scheme_used: None,
+                        latency_ms: 0,
+                        error_kind: Some("nxdomain".to_string()),
+                    }
+                }
+                DnsOutcome::Bogus => {
+                    write_to_file(&BOGUS_FILE, &domain).await;
+                    DomainResult {
+                        domain: domain.clone(),
+                        final_url: None,
+                        status: "bogus".to_string(),
+                        scheme_used: None,
+                        latency_ms: 0,
+                        error_kind: Some("dnssec_validation_failed".to_string()),
+                    }
+                }
+                DnsOutcome::ResolutionError => {
+                    write_to_file(&DEAD_FILE, &domain).await;
+                    DomainResult {
+                        domain: domain.clone(),
+                        final_url: None,
+                        status: "dead".to_string(),
+                        scheme_used: None,
+                        latency_ms: 0,
+                        error_kind: Some("dns_resolution_failed".to_string()),
+                    }
+                }
+                DnsOutcome::Resolved(_) if dns_only => DomainResult {
+                    domain: domain.clone(),
+                    final_url: None,
+                    status: "resolved".to_string(),
+                    scheme_used: None,
+                    latency_ms: 0,
+                    error_kind: None,
+                },
+                DnsOutcome::Resolved(addrs) => {
+                    let result = check_domain(&client, &domain, &retry_policy).await;
+                    if result.status == "live" && tls_info && result.scheme_used.as_deref() == Some("https") {
+                        inspect_and_record_tls(&domain, &addrs, expiry_warn_days).await;
+                    }
+                    result
+                }
+            };
+
+            result.latency_ms = start.elapsed().as_millis();
+            emit_result(result, output_format, verbose).await;
         });
 
         handles.push(handle);
@@ -72,51 +391,408 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let _ = handle.await;
     }
 
-    println!("Domain check completed.");
+    if output_format == OutputFormat::Text {
+        println!("Domain check completed.");
+    }
+
+    print_summary().await;
     Ok(())
 }
 
-// Check a domain using both HTTP and HTTPS
-async fn check_domain(client: &Client, domain: &str, verbose: bool) {
+// Print per-status counts and latency percentiles for the whole run to
+// stderr, so structured stdout output stays pipeline-clean.
+async fn print_summary() {
+    let results = RESULTS.lock().await;
+    let mut counts: std::collections::BTreeMap<&str, usize> = std::collections::BTreeMap::new();
+    let mut latencies: Vec<u128> = Vec::with_capacity(results.len());
+
+    for result in results.iter() {
+        *counts.entry(result.status.as_str()).or_insert(0) += 1;
+        latencies.push(result.latency_ms);
+    }
+    latencies.sort_unstable();
+
+    eprintln!("--- summary ---");
+    for (status, count) in &counts {
+        eprintln!("{}: {}", status, count);
+    }
+    if let Some(median) = percentile(&latencies, 0.50) {
+        eprintln!("latency p50: {}ms", median);
+    }
+    if let Some(p95) = percentile(&latencies, 0.95) {
+        eprintln!("latency p95: {}ms", p95);
+    }
+}
+
+// Nearest-rank percentile over an already-sorted slice
+fn percentile(sorted: &[u128], p: f64) -> Option<u128> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((sorted.len() as f64) * p).ceil() as usize;
+    let index = rank.saturating_sub(1).min(sorted.len() - 1);
+    Some(sorted[index])
+}
+
+// Print (or skip) the per-domain record according to `--output-format`, then
+// retain it for the end-of-run summary.
+async fn emit_result(result: DomainResult, format: OutputFormat, verbose: bool) {
+    match format {
+        OutputFormat::Text => println!("{}", format_text(&result, verbose)),
+        OutputFormat::Ndjson => {
+            if let Ok(line) = serde_json::to_string(&result) {
+                println!("{}", line);
+            }
+        }
+        OutputFormat::Csv => println!("{}", result.to_csv_row()),
+    }
+
+    RESULTS.lock().await.push(result);
+}
+
+// Render a DomainResult the way the original emoji-prefixed messages did
+fn format_text(result: &DomainResult, verbose: bool) -> String {
+    match result.status.as_str() {
+        "live" => {
+            let url = result.final_url.as_deref().unwrap_or(&result.domain);
+            if verbose {
+                format!("✓ {} - Active ({}ms)", url, result.latency_ms)
+            } else {
+                format!("✓ {} - Active", url)
+            }
+        }
+        "nxdomain" => format!("✗ {} - NXDOMAIN (does not resolve)", result.domain),
+        "resolved" => format!("✓ {} - resolves", result.domain),
+        _ => {
+            if verbose {
+                match result.error_kind.as_deref() {
+                    Some("unreachable") | None => format!("✗ {} - Failed (Tried both HTTP & HTTPS)", result.domain),
+                    Some(kind) => format!("✗ {} - Failed ({})", result.domain, kind),
+                }
+            } else {
+                format!("✗ {} - Failed", result.domain)
+            }
+        }
+    }
+}
+
+// Build an async DNS resolver, optionally pointed at a user-supplied nameserver
+// instead of the system configuration (/etc/resolv.conf).
+fn build_resolver(custom: Option<&str>) -> Result<TokioAsyncResolver, Box<dyn std::error::Error>> {
+    match custom {
+        Some(addr) => {
+            let socket_addr: SocketAddr = addr.parse()?;
+            let config = ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(&[socket_addr.ip()], socket_addr.port(), true),
+            );
+            Ok(TokioAsyncResolver::tokio(config, ResolverOpts::default()))
+        }
+        None => Ok(TokioAsyncResolver::tokio_from_system_conf()?),
+    }
+}
+
+// Resolve a domain's A/AAAA records (following CNAMEs), distinguishing a hard
+// NXDOMAIN from other resolution failures so permits aren't wasted dialing
+// domains that will never answer.
+async fn resolve_domain(resolver: &TokioAsyncResolver, domain: &str) -> DnsOutcome {
+    match resolver.lookup_ip(domain).await {
+        Ok(lookup) => {
+            let addrs: Vec<IpAddr> = lookup.iter().collect();
+            if addrs.is_empty() {
+                DnsOutcome::NxDomain
+            } else {
+                DnsOutcome::Resolved(addrs)
+            }
+        }
+        Err(e) => match e.kind() {
+            ResolveErrorKind::NoRecordsFound { .. } => DnsOutcome::NxDomain,
+            _ => DnsOutcome::ResolutionError,
+        },
+    }
+}
+
+// Resolve a domain over DNS-over-HTTPS (RFC 8484): wire-encode an A query and
+// an AAAA query (setting the DO bit when DNSSEC is requested), GET each
+// base64url-encoded against the DoH endpoint, and parse the binary response.
+// Both QTYPEs are queried — a single query can only carry one QTYPE — and
+// resolved so an AAAA-only host doesn't look bogus/NXDOMAIN next to
+// `resolve_domain`'s `lookup_ip` (chunk0-1), which checks both.
+//
+// DNSSEC note: this only checks the AD (Authenticated Data) bit the DoH
+// resolver asserts in its response header. A full chain-of-trust walk
+// (DNSKEY/DS/RRSIG up to the hard-coded root KSK, per RFC 4035) is not
+// implemented here — that requires a verifier like the dnssec-prover crate
+// wired in end-to-end, which is a larger follow-up. A resolver that lies
+// about the AD bit (or a network path that strips HTTPS) is not caught; see
+// the --dnssec startup warning.
+async fn resolve_via_doh(http_client: &Client, doh_url: &str, domain: &str, want_dnssec: bool) -> DnsOutcome {
+    let a = query_doh(http_client, doh_url, domain, RecordType::A, want_dnssec).await;
+    let aaaa = query_doh(http_client, doh_url, domain, RecordType::AAAA, want_dnssec).await;
+
+    let mut addrs = Vec::new();
+    let mut authenticated = true;
+    let mut saw_nxdomain = false;
+    let mut saw_error = false;
+
+    for outcome in [a, aaaa] {
+        match outcome {
+            DohQueryOutcome::Answered { addrs: mut these, authentic_data } => {
+                if want_dnssec && !authentic_data {
+                    authenticated = false;
+                }
+                addrs.append(&mut these);
+            }
+            DohQueryOutcome::NxDomain => saw_nxdomain = true,
+            DohQueryOutcome::Error => saw_error = true,
+        }
+    }
+
+    if !addrs.is_empty() {
+        return if want_dnssec && !authenticated { DnsOutcome::Bogus } else { DnsOutcome::Resolved(addrs) };
+    }
+    if saw_nxdomain && !saw_error {
+        return DnsOutcome::NxDomain;
+    }
+    DnsOutcome::ResolutionError
+}
+
+// Outcome of a single QTYPE's DoH query, before the A/AAAA results are
+// merged by `resolve_via_doh`.
+enum DohQueryOutcome {
+    Answered { addrs: Vec<IpAddr>, authentic_data: bool },
+    NxDomain,
+    Error,
+}
+
+// Issue one DoH query for `qtype` and parse its answer section.
+async fn query_doh(http_client: &Client, doh_url: &str, domain: &str, qtype: RecordType, dnssec: bool) -> DohQueryOutcome {
+    let query = match build_doh_query(domain, qtype, dnssec) {
+        Ok(q) => q,
+        Err(_) => return DohQueryOutcome::Error,
+    };
+    let encoded = URL_SAFE_NO_PAD.encode(query);
+
+    let response = http_client.get(doh_url)
+        .query(&[("dns", encoded)])
+        .header(reqwest::header::ACCEPT, "application/dns-message")
+        .send()
+        .await;
+
+    let bytes = match response {
+        Ok(resp) if resp.status().is_success() => match resp.bytes().await {
+            Ok(b) => b,
+            Err(_) => return DohQueryOutcome::Error,
+        },
+        _ => return DohQueryOutcome::Error,
+    };
+
+    let message = match Message::from_vec(&bytes) {
+        Ok(m) => m,
+        Err(_) => return DohQueryOutcome::Error,
+    };
+
+    if message.response_code() == ResponseCode::NXDomain {
+        return DohQueryOutcome::NxDomain;
+    }
+
+    // Negative answers (NSEC/NSEC3-backed) also arrive as NOERROR with an
+    // empty answer section; treat them the same as NXDOMAIN rather than a
+    // resolver failure.
+    let addrs: Vec<IpAddr> = message.answers().iter().filter_map(|r| match r.data() {
+        Some(RData::A(a)) => Some(IpAddr::V4(a.0)),
+        Some(RData::AAAA(aaaa)) => Some(IpAddr::V6(aaaa.0)),
+        _ => None,
+    }).collect();
+    if addrs.is_empty() {
+        return DohQueryOutcome::NxDomain;
+    }
+
+    DohQueryOutcome::Answered { addrs, authentic_data: message.header().authentic_data() }
+}
+
+// Build the wire-format DNS query for a single QTYPE's DoH request
+fn build_doh_query(domain: &str, qtype: RecordType, dnssec: bool) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // `Name::from_ascii("")` happily parses as the DNS root name rather than
+    // erroring, which isn't a domain anyone passed in here.
+    if domain.is_empty() {
+        return Err("domain must not be empty".into());
+    }
+    let name = Name::from_ascii(domain)?;
+
+    let mut message = Message::new();
+    message.set_id(0)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true);
+    message.add_query(Query::query(name, qtype));
+
+    if dnssec {
+        let mut edns = Edns::new();
+        edns.set_dnssec_ok(true);
+        message.set_edns(edns);
+    }
+
+    Ok(message.to_vec()?)
+}
+
+// Check a domain using both HTTP and HTTPS, returning a structured result
+// (latency_ms is filled in by the caller, which times the whole DNS+HTTP check)
+async fn check_domain(client: &Client, domain: &str, retry_policy: &RetryPolicy) -> DomainResult {
     let request_timeout = Duration::from_secs(10);
 
     let http_url = format!("http://{}", domain);
     let https_url = format!("https://{}", domain);
 
-    if let Some(success) = test_url(client, &http_url, request_timeout, verbose).await {
-        println!("{}", success);
-        return;
+    if let Ok((final_url, _status)) = test_url_with_retries(client, &http_url, request_timeout, retry_policy).await {
+        write_to_file(&LIVE_FILE, domain).await;
+        return DomainResult {
+            domain: domain.to_string(),
+            final_url: Some(final_url),
+            status: "live".to_string(),
+            scheme_used: Some("http".to_string()),
+            latency_ms: 0,
+            error_kind: None,
+        };
     }
 
-    if let Some(success) = test_url(client, &https_url, request_timeout, verbose).await {
-        println!("{}", success);
-        return;
+    if let Ok((final_url, _status)) = test_url_with_retries(client, &https_url, request_timeout, retry_policy).await {
+        write_to_file(&LIVE_FILE, domain).await;
+        return DomainResult {
+            domain: domain.to_string(),
+            final_url: Some(final_url),
+            status: "live".to_string(),
+            scheme_used: Some("https".to_string()),
+            latency_ms: 0,
+            error_kind: None,
+        };
     }
 
     write_to_file(&DEAD_FILE, domain).await;
-    println!("{}", format_failure(domain, verbose));
+    DomainResult {
+        domain: domain.to_string(),
+        final_url: None,
+        status: "dead".to_string(),
+        scheme_used: None,
+        latency_ms: 0,
+        error_kind: Some("unreachable".to_string()),
+    }
 }
 
-// Test a single URL
-async fn test_url(client: &Client, url: &str, timeout_duration: Duration, verbose: bool) -> Option<String> {
-    match timeout(timeout_duration, client.head(url).send()).await {
-        Ok(Ok(response)) if response.status().is_success() => {
-            let domain = extract_domain(url);
-            write_to_file(&LIVE_FILE, &domain).await;
-            Some(format_success(url, response.status(), verbose))
+// Test a URL, retrying transient failures (timeouts, connection errors, and
+// statuses in `retry_statuses`) with exponential backoff before giving up.
+async fn test_url_with_retries(client: &Client, url: &str, timeout_duration: Duration, retry_policy: &RetryPolicy) -> Result<(String, u16), Option<u16>> {
+    let mut attempt = 0;
+    loop {
+        match test_url(client, url, timeout_duration).await {
+            Ok(outcome) => return Ok(outcome),
+            Err(status) => {
+                let errored = status.is_none();
+                if attempt >= retry_policy.retries || !retry_policy.should_retry(status, errored) {
+                    return Err(status);
+                }
+                tokio::time::sleep(retry_policy.backoff_for(attempt)).await;
+                attempt += 1;
+            }
         }
-        _ => None,
     }
 }
 
-// Extract domain from URL
-fn extract_domain(url: &str) -> String {
-    url.replace("http://", "")
-       .replace("https://", "")
-       .split('/')
-       .next()
-       .unwrap_or(url)
-       .to_string()
+// Perform a direct TLS handshake against a live HTTPS domain, parse its leaf
+// certificate, and append a row to certs.txt. Bypasses `client.head()` since
+// reqwest doesn't expose the peer certificate chain. Connects to one of the
+// addresses the DNS pre-check stage already resolved (via `--resolver` or
+// `--doh`) rather than re-resolving through the system resolver. The HTTP
+// liveness check resolves through reqwest's own resolver (and any `--proxy`),
+// so the two checks can reach different addresses.
+async fn inspect_and_record_tls(domain: &str, addrs: &[IpAddr], expiry_warn_days: i64) {
+    let Some(&ip) = addrs.first() else {
+        write_to_file(&CERTS_FILE, &format!("{}\terror=no resolved address available", domain)).await;
+        return;
+    };
+
+    match fetch_cert_info(ip, domain, expiry_warn_days).await {
+        Ok(info) => {
+            let warn_marker = if info.expires_soon { " [EXPIRING SOON]" } else { "" };
+            let line = format!(
+                "{}\tissuer={}\tsubject={}\tsans={}\tnot_before={}\tnot_after={}{}",
+                domain, info.issuer, info.subject, info.sans.join(","), info.not_before, info.not_after, warn_marker
+            );
+            write_to_file(&CERTS_FILE, &line).await;
+        }
+        Err(e) => {
+            write_to_file(&CERTS_FILE, &format!("{}\terror={}", domain, e)).await;
+        }
+    }
+}
+
+// Connect over TLS to `ip` (SNI/cert verification still use `domain`) and
+// extract issuer/subject/SANs/validity from the presented leaf certificate's
+// DER encoding.
+async fn fetch_cert_info(ip: IpAddr, domain: &str, expiry_warn_days: i64) -> Result<CertInfo, Box<dyn std::error::Error + Send + Sync>> {
+    let mut root_store = RootCertStore::empty();
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+    }));
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = domain.try_into()?;
+
+    let tcp = TcpStream::connect((ip, 443)).await?;
+    let tls_stream = connector.connect(server_name, tcp).await?;
+
+    let certs = tls_stream.get_ref().1.peer_certificates().ok_or("no certificate presented")?;
+    let leaf_der = certs.first().ok_or("empty certificate chain")?;
+
+    let (_, cert) = X509Certificate::from_der(leaf_der.as_ref())?;
+
+    let not_before = cert.validity().not_before.to_string();
+    let not_after_dt = cert.validity().not_after;
+    let not_after = not_after_dt.to_string();
+    let expires_soon = not_after_dt.timestamp() - now_unix() < expiry_warn_days * 86_400;
+
+    let sans = cert.subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|ext| ext.value.general_names.iter().map(|name| name.to_string()).collect())
+        .unwrap_or_default();
+
+    Ok(CertInfo {
+        issuer: cert.issuer().to_string(),
+        subject: cert.subject().to_string(),
+        sans,
+        not_before,
+        not_after,
+        expires_soon,
+    })
+}
+
+// Current time as a Unix timestamp, used for certificate expiry comparisons
+fn now_unix() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// Test a single URL. Returns the final URL and status on success; the
+// presented status on a non-success response so the caller can decide
+// whether it's worth retrying; `Err(None)` marks a timeout or
+// connection-level failure.
+async fn test_url(client: &Client, url: &str, timeout_duration: Duration) -> Result<(String, u16), Option<u16>> {
+    match timeout(timeout_duration, client.head(url).send()).await {
+        Ok(Ok(response)) if response.status().is_success() => {
+            Ok((response.url().to_string(), response.status().as_u16()))
+        }
+        Ok(Ok(response)) => Err(Some(response.status().as_u16())),
+        _ => Err(None),
+    }
 }
 
 // Write to a file safely using async-friendly Mutex
@@ -126,22 +802,120 @@ async fn write_to_file(file: &Arc<Mutex<BufWriter<File>>>, domain: &str) {
     let _ = file.flush();
 }
 
-// Format success message
-fn format_success(url: &str, status: reqwest::StatusCode, verbose: bool) -> String {
-    if verbose {
-        format!("✓ {} - Active (Status: {})", url, status)
-    } else {
-        format!("✓ {} - Active", url)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            backoff: Duration::from_millis(200),
+            retry_statuses: vec![429, 500, 502, 503, 504],
+        }
     }
-}
 
-// Format failure message
-fn format_failure(domain: &str, verbose: bool) -> String {
-    if verbose {
-        format!("✗ {} - Failed (Tried both HTTP & HTTPS)", domain)
-    } else {
-        format!("✗ {} - Failed", domain)
+    #[test]
+    fn should_retry_on_connection_error() {
+        assert!(policy(1).should_retry(None, true));
+    }
+
+    #[test]
+    fn should_retry_on_listed_status() {
+        assert!(policy(1).should_retry(Some(503), false));
+    }
+
+    #[test]
+    fn should_not_retry_on_unlisted_status() {
+        assert!(!policy(1).should_retry(Some(404), false));
+    }
+
+    #[test]
+    fn should_not_retry_on_success_status() {
+        assert!(!policy(1).should_retry(Some(200), false));
+    }
+
+    #[test]
+    fn backoff_doubles_each_attempt() {
+        let p = policy(5);
+        assert_eq!(p.backoff_for(0), Duration::from_millis(200));
+        assert_eq!(p.backoff_for(1), Duration::from_millis(400));
+        assert_eq!(p.backoff_for(2), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn backoff_saturates_instead_of_overflowing() {
+        let p = policy(1000);
+        assert_eq!(p.backoff_for(u32::MAX), Duration::from_millis(200) * 2u32.saturating_pow(u32::MAX));
     }
-}
 
+    #[test]
+    fn percentile_of_empty_is_none() {
+        assert_eq!(percentile(&[], 0.50), None);
+    }
+
+    #[test]
+    fn percentile_nearest_rank() {
+        let sorted = [10, 20, 30, 40, 50];
+        assert_eq!(percentile(&sorted, 0.50), Some(30));
+        assert_eq!(percentile(&sorted, 0.95), Some(50));
+        assert_eq!(percentile(&sorted, 1.0), Some(50));
+    }
+
+    #[test]
+    fn percentile_single_element() {
+        assert_eq!(percentile(&[42], 0.50), Some(42));
+    }
+
+    #[test]
+    fn output_format_parses_known_values() {
+        assert!(matches!(OutputFormat::parse("text"), Ok(OutputFormat::Text)));
+        assert!(matches!(OutputFormat::parse("ndjson"), Ok(OutputFormat::Ndjson)));
+        assert!(matches!(OutputFormat::parse("csv"), Ok(OutputFormat::Csv)));
+    }
+
+    #[test]
+    fn output_format_rejects_unknown_value() {
+        assert!(OutputFormat::parse("yaml").is_err());
+    }
 
+    #[test]
+    fn csv_field_passes_through_plain_values() {
+        assert_eq!(csv_field("live"), "live");
+    }
+
+    #[test]
+    fn csv_field_quotes_commas() {
+        assert_eq!(csv_field("https://example.com/?a=1,2"), "\"https://example.com/?a=1,2\"");
+    }
+
+    #[test]
+    fn csv_field_escapes_embedded_quotes() {
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn doh_query_rejects_empty_domain() {
+        assert!(build_doh_query("", RecordType::A, false).is_err());
+    }
+
+    #[test]
+    fn doh_query_sets_requested_qtype() {
+        let wire = build_doh_query("example.com", RecordType::AAAA, false).unwrap();
+        let message = Message::from_vec(&wire).unwrap();
+        assert_eq!(message.queries()[0].query_type(), RecordType::AAAA);
+    }
+
+    #[test]
+    fn doh_query_sets_do_bit_when_dnssec_requested() {
+        let wire = build_doh_query("example.com", RecordType::A, true).unwrap();
+        let message = Message::from_vec(&wire).unwrap();
+        assert!(message.extensions().as_ref().is_some_and(|e| e.dnssec_ok()));
+    }
+
+    #[test]
+    fn doh_query_omits_edns_when_dnssec_not_requested() {
+        let wire = build_doh_query("example.com", RecordType::A, false).unwrap();
+        let message = Message::from_vec(&wire).unwrap();
+        assert!(message.extensions().is_none());
+    }
+}